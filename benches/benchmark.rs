@@ -3,16 +3,30 @@ use dmatcher::Dmatcher;
 use std::fs::File;
 use std::io::Read;
 
-fn bench_match(c: &mut Criterion) {
+fn load_contents() -> String {
     let mut file = File::open("./benches/accelerated-domains.china.raw.txt").unwrap();
     let mut contents = String::new();
-    let mut matcher = Dmatcher::new();
     file.read_to_string(&mut contents).unwrap();
-    matcher.insert_lines(&contents);
+    contents
+}
+
+fn bench_match(c: &mut Criterion) {
+    let mut matcher = Dmatcher::new();
+    matcher.insert_lines(load_contents(), 1).unwrap();
     c.bench_function("match", |b| {
         b.iter(|| matcher.matches("你好.store.www.baidu.com"))
     });
 }
 
-criterion_group!(benches, bench_match);
+fn bench_frozen_match(c: &mut Criterion) {
+    let mut matcher = Dmatcher::new();
+    matcher.insert_lines(load_contents(), 1).unwrap();
+    matcher.optimize();
+    let frozen = matcher.freeze();
+    c.bench_function("frozen match", |b| {
+        b.iter(|| frozen.matches("你好.store.www.baidu.com"))
+    });
+}
+
+criterion_group!(benches, bench_match, bench_frozen_match);
 criterion_main!(benches);
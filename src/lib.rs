@@ -17,21 +17,55 @@
 //! ```
 
 use hashbrown::HashMap;
+use regex::Regex;
 use trust_dns_proto::error::ProtoResult;
 use trust_dns_proto::rr::domain::IntoName;
 use trust_dns_proto::rr::domain::Label;
+use trust_dns_proto::rr::domain::Name;
+
+/// Public Suffix List support: registrable-domain (eTLD+1) extraction built on top of the
+/// same label trie.
+pub mod psl;
+
+/// The value held by a trie node: either a positive rule, an exception (negation) rule, or
+/// nothing at all.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub(crate) enum NodeValue<T> {
+    /// A normal, positive rule carrying its associated data.
+    Allow(T),
+    /// An exception rule: overrides any broader ancestor `Allow` so the label trie yields no
+    /// match; `matches` still consults the `pre` and `post` regex rules.
+    Deny,
+    /// No rule at this node.
+    None,
+}
+
+impl<T: Copy> NodeValue<T> {
+    pub(crate) fn is_none(&self) -> bool {
+        matches!(self, NodeValue::None)
+    }
+
+    fn into_option(self) -> Option<T> {
+        match self {
+            NodeValue::Allow(v) => Some(v),
+            NodeValue::Deny | NodeValue::None => None,
+        }
+    }
+}
 
 #[derive(Debug, PartialEq, Clone)]
-struct LevelNode<T: Copy> {
-    dst: Option<T>,
-    next_lvs: HashMap<Label, LevelNode<T>>,
+pub(crate) struct LevelNode<T: Copy> {
+    pub(crate) dst: NodeValue<T>,
+    pub(crate) next_lvs: HashMap<Label, LevelNode<T>>,
+    pub(crate) wildcard: Option<Box<LevelNode<T>>>,
 }
 
 impl<T: Copy> LevelNode<T> {
     fn new() -> Self {
         Self {
-            dst: None,
+            dst: NodeValue::None,
             next_lvs: HashMap::new(),
+            wildcard: None,
         }
     }
 }
@@ -40,6 +74,10 @@ impl<T: Copy> LevelNode<T> {
 /// Dmatcher matcher algorithm
 pub struct Dmatcher<T: Copy> {
     root: LevelNode<T>,
+    // Linear rule lists tried before/after the label trie, following the sozu router design,
+    // for the handful of patterns that don't fit label-granular suffix matching.
+    pre: Vec<(Regex, T)>,
+    post: Vec<(Regex, T)>,
 }
 
 impl<T: Copy> Default for Dmatcher<T> {
@@ -53,14 +91,63 @@ impl<T: Copy> Dmatcher<T> {
     pub fn new() -> Self {
         Self {
             root: LevelNode::new(),
+            pre: Vec::new(),
+            post: Vec::new(),
         }
     }
 
-    #[cfg(test)]
-    fn get_root(&self) -> &LevelNode<T> {
+    /// Insert a regex rule that is tried, in insertion order, before the label trie.
+    pub fn insert_pre_regex(&mut self, pattern: &str, dst: T) -> Result<(), regex::Error> {
+        self.pre.push((Regex::new(pattern)?, dst));
+        Ok(())
+    }
+
+    /// Insert a regex rule that is tried, in insertion order, after the label trie has found
+    /// no match.
+    pub fn insert_post_regex(&mut self, pattern: &str, dst: T) -> Result<(), regex::Error> {
+        self.post.push((Regex::new(pattern)?, dst));
+        Ok(())
+    }
+
+    /// Access the root of the label trie directly; used by the public-suffix-list extension,
+    /// which needs a different (non-block-list) resolution walk over the same structure.
+    pub(crate) fn root(&self) -> &LevelNode<T> {
         &self.root
     }
 
+    /// Flatten this matcher into an immutable, lookup-only [`FrozenDmatcher`]: nodes move from
+    /// a tree of per-node `HashMap`s into a single contiguous array, with each node's children
+    /// held as a label-sorted slice searched with a binary search. This is smaller and more
+    /// cache-friendly to walk than the mutable form; call [`Dmatcher::optimize`] first to also
+    /// collapse redundant subtrees before freezing.
+    pub fn freeze(self) -> FrozenDmatcher<T> {
+        let mut nodes = Vec::new();
+        let root = Self::flatten(self.root, &mut nodes);
+        FrozenDmatcher {
+            nodes,
+            root,
+            pre: self.pre,
+            post: self.post,
+        }
+    }
+
+    fn flatten(node: LevelNode<T>, nodes: &mut Vec<FrozenNode<T>>) -> u32 {
+        let wildcard = node.wildcard.map(|w| Self::flatten(*w, nodes));
+        let mut children: Vec<(Label, u32)> = node
+            .next_lvs
+            .into_iter()
+            .map(|(label, child)| (label, Self::flatten(child, nodes)))
+            .collect();
+        children.sort_by(|(a, _), (b, _)| a.cmp(b));
+        let idx = nodes.len() as u32;
+        nodes.push(FrozenNode {
+            dst: node.dst,
+            children,
+            wildcard,
+        });
+        idx
+    }
+
     /// Pass in a string containing `\n` and get all domains inserted.
     pub fn insert_lines(&mut self, domain: String, dst: T) -> ProtoResult<()> {
         let lvs: Vec<&str> = domain.split('\n').collect();
@@ -70,43 +157,239 @@ impl<T: Copy> Dmatcher<T> {
         Ok(())
     }
 
-    /// Pass in a domain and insert it into the matcher.
-    pub fn insert<U: IntoName>(&mut self, domain: U, dst: T) -> ProtoResult<()> {
+    /// Walk (creating nodes as needed) to the node for `domain`, descending into the
+    /// wildcard child whenever a label is `*`.
+    fn descend<U: IntoName>(&mut self, domain: U) -> ProtoResult<&mut LevelNode<T>> {
         let lvs = U::into_name(domain)?;
         let lvs = lvs.iter().rev();
         let mut ptr = &mut self.root;
         for lv in lvs {
-            ptr = ptr
-                .next_lvs
-                .entry(Label::from_raw_bytes(lv)?)
-                .or_insert_with(LevelNode::new);
+            ptr = if lv == b"*" {
+                ptr.wildcard.get_or_insert_with(|| Box::new(LevelNode::new()))
+            } else {
+                ptr.next_lvs
+                    .entry(Label::from_raw_bytes(lv)?)
+                    .or_insert_with(LevelNode::new)
+            };
         }
-        ptr.dst = Some(dst);
+        Ok(ptr)
+    }
+
+    /// Pass in a domain and insert it into the matcher. A label of `*` is treated as a
+    /// single-label wildcard, e.g. `*.cdn.example.com` matches exactly one arbitrary label
+    /// at that position.
+    pub fn insert<U: IntoName>(&mut self, domain: U, dst: T) -> ProtoResult<()> {
+        self.descend(domain)?.dst = NodeValue::Allow(dst);
         Ok(())
     }
 
-    /// Match the domain against inserted domain rules. If `apple.com` is inserted, then `www.apple.com` and `stores.www.apple.com` is considered as matched while `apple.cn` is not.
-    pub fn matches<U: IntoName>(&self, domain: U) -> ProtoResult<Option<T>> {
-        let lvs = U::into_name(domain)?;
-        let lvs = lvs.iter().rev();
+    /// Insert an exception (negation) rule. If the most specific rule along a query's path
+    /// is an exception, the label trie yields no match even when a broader ancestor carries a
+    /// positive rule, mirroring hosts-file/adblock allow-list semantics; `matches` then returns
+    /// `Ok(None)` unless a `pre` or `post` regex rule matches the name.
+    pub fn insert_exception<U: IntoName>(&mut self, domain: U) -> ProtoResult<()> {
+        self.descend(domain)?.dst = NodeValue::Deny;
+        Ok(())
+    }
+
+    /// Walk the label trie looking for the most specific rule matching `name`. See `matches`
+    /// for the exact/wildcard/ancestor-fallback semantics.
+    fn trie_match(&self, name: &Name) -> ProtoResult<Option<T>> {
+        let mut lvs = name.iter().rev().peekable();
         let mut ptr = &self.root;
-        for lv in lvs {
-            if ptr.next_lvs.is_empty() {
-                break;
+        let mut best = ptr.dst;
+        // Set while `ptr` was reached through a wildcard: its rule covers only that one label,
+        // so it is no fallback for longer names.
+        let mut via_wildcard = false;
+        while let Some(lv) = lvs.next() {
+            if !via_wildcard && !ptr.dst.is_none() {
+                best = ptr.dst;
             }
-            // If not empty...
+            via_wildcard = false;
             ptr = match ptr.next_lvs.get(&Label::from_raw_bytes(lv)?) {
                 Some(v) => v,
-                None => return Ok(None),
+                // A wildcard only ever consumes the single label it stands in for; if
+                // nothing is defined past it, any further labels are not a match.
+                None => match &ptr.wildcard {
+                    Some(w) if lvs.peek().is_some() && w.next_lvs.is_empty() && w.wildcard.is_none() => {
+                        return Ok(best.into_option())
+                    }
+                    Some(w) => {
+                        via_wildcard = true;
+                        w
+                    }
+                    None => return Ok(best.into_option()),
+                },
             };
         }
-        Ok(ptr.dst)
+        if !ptr.dst.is_none() {
+            best = ptr.dst;
+        }
+        Ok(best.into_option())
+    }
+
+    /// Match the domain against inserted domain rules. If `apple.com` is inserted, then `www.apple.com` and `stores.www.apple.com` is considered as matched while `apple.cn` is not.
+    /// Exact labels always take priority over a wildcard rule at the same depth, so
+    /// `api.example.com` and `*.example.com` can coexist with the more specific rule winning.
+    /// Matching is longest-suffix: if both `com` and `apple.com` are inserted, querying
+    /// `foo.com` falls back to the `com` rule instead of failing outright.
+    ///
+    /// Before consulting the trie, `pre` regex rules are tried in insertion order against the
+    /// full domain; if none hit and the trie itself has no match, `post` regex rules are tried
+    /// as a final fallback.
+    pub fn matches<U: IntoName>(&self, domain: U) -> ProtoResult<Option<T>> {
+        let name = U::into_name(domain)?;
+        let full = name.to_string();
+        if let Some(dst) = self
+            .pre
+            .iter()
+            .find(|(re, _)| re.is_match(&full))
+            .map(|(_, dst)| *dst)
+        {
+            return Ok(Some(dst));
+        }
+        if let Some(dst) = self.trie_match(&name)? {
+            return Ok(Some(dst));
+        }
+        Ok(self
+            .post
+            .iter()
+            .find(|(re, _)| re.is_match(&full))
+            .map(|(_, dst)| *dst))
+    }
+}
+
+impl<T: Copy + PartialEq> Dmatcher<T> {
+    /// Collapse redundant subtrees built up by `insert_lines`: working bottom-up, a childless
+    /// node carrying the same `Allow` value as its parent is dropped, since `matches`'
+    /// most-specific-ancestor fallback resolves it to that value anyway. Exact children are
+    /// kept while the parent still has a wildcard child. Nodes without a rule of their own are
+    /// never dropped, so a subtree collapses only where every node in it carries that value.
+    pub fn optimize(&mut self) {
+        Self::optimize_node(&mut self.root);
+    }
+
+    fn optimize_node(node: &mut LevelNode<T>) {
+        for child in node.next_lvs.values_mut() {
+            Self::optimize_node(child);
+        }
+        if let Some(w) = node.wildcard.as_mut() {
+            Self::optimize_node(w);
+        }
+        if let NodeValue::Allow(v) = node.dst {
+            if matches!(&node.wildcard, Some(w) if Self::is_redundant_leaf(w, v)) {
+                node.wildcard = None;
+            }
+            // An exact child shadows the wildcard, so without it the query would fall to the
+            // wildcard rather than to this node.
+            if node.wildcard.is_none() {
+                node.next_lvs
+                    .retain(|_, child| !Self::is_redundant_leaf(child, v));
+            }
+        }
+    }
+
+    /// A childless node carrying the same value as its parent adds nothing: any query that
+    /// reaches it would already resolve to that value at the parent via ancestor fallback.
+    fn is_redundant_leaf(node: &LevelNode<T>, parent_value: T) -> bool {
+        node.dst == NodeValue::Allow(parent_value) && node.next_lvs.is_empty() && node.wildcard.is_none()
+    }
+}
+
+#[derive(Debug, Clone)]
+struct FrozenNode<T: Copy> {
+    dst: NodeValue<T>,
+    // Sorted by label so a child is located with a binary search instead of a hash lookup.
+    children: Vec<(Label, u32)>,
+    wildcard: Option<u32>,
+}
+
+/// An immutable, lookup-only form of a [`Dmatcher`], produced by [`Dmatcher::freeze`].
+///
+/// Nodes live in a single contiguous array rather than a tree of heap-allocated `HashMap`s,
+/// which cuts per-node overhead and improves cache locality at lookup time; see the `benches`
+/// crate for the effect on the 73k+ domain set. `matches` has identical semantics to
+/// [`Dmatcher::matches`].
+#[derive(Debug, Clone)]
+pub struct FrozenDmatcher<T: Copy> {
+    nodes: Vec<FrozenNode<T>>,
+    root: u32,
+    pre: Vec<(Regex, T)>,
+    post: Vec<(Regex, T)>,
+}
+
+impl<T: Copy> FrozenDmatcher<T> {
+    fn node(&self, idx: u32) -> &FrozenNode<T> {
+        &self.nodes[idx as usize]
+    }
+
+    fn child(&self, node: &FrozenNode<T>, label: &Label) -> Option<u32> {
+        node.children
+            .binary_search_by(|(l, _)| l.cmp(label))
+            .ok()
+            .map(|i| node.children[i].1)
+    }
+
+    fn trie_match(&self, name: &Name) -> ProtoResult<Option<T>> {
+        let mut lvs = name.iter().rev().peekable();
+        let mut ptr = self.node(self.root);
+        let mut best = ptr.dst;
+        let mut via_wildcard = false;
+        while let Some(lv) = lvs.next() {
+            if !via_wildcard && !ptr.dst.is_none() {
+                best = ptr.dst;
+            }
+            via_wildcard = false;
+            let label = Label::from_raw_bytes(lv)?;
+            ptr = match self.child(ptr, &label) {
+                Some(idx) => self.node(idx),
+                None => match ptr.wildcard {
+                    Some(idx) => {
+                        let w = self.node(idx);
+                        if lvs.peek().is_some() && w.children.is_empty() && w.wildcard.is_none() {
+                            return Ok(best.into_option());
+                        }
+                        via_wildcard = true;
+                        w
+                    }
+                    None => return Ok(best.into_option()),
+                },
+            };
+        }
+        if !ptr.dst.is_none() {
+            best = ptr.dst;
+        }
+        Ok(best.into_option())
+    }
+
+    /// Match the domain against the frozen rule set. See [`Dmatcher::matches`] for the
+    /// exact/wildcard/ancestor-fallback/pre-post-regex semantics, all of which carry over
+    /// unchanged.
+    pub fn matches<U: IntoName>(&self, domain: U) -> ProtoResult<Option<T>> {
+        let name = U::into_name(domain)?;
+        let full = name.to_string();
+        if let Some(dst) = self
+            .pre
+            .iter()
+            .find(|(re, _)| re.is_match(&full))
+            .map(|(_, dst)| *dst)
+        {
+            return Ok(Some(dst));
+        }
+        if let Some(dst) = self.trie_match(&name)? {
+            return Ok(Some(dst));
+        }
+        Ok(self
+            .post
+            .iter()
+            .find(|(re, _)| re.is_match(&full))
+            .map(|(_, dst)| *dst))
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{Dmatcher, LevelNode};
+    use super::{Dmatcher, LevelNode, NodeValue};
     use hashbrown::HashMap;
     use trust_dns_proto::error::ProtoResult;
     use trust_dns_proto::rr::domain::Label;
@@ -122,25 +405,122 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn wildcard() -> ProtoResult<()> {
+        let mut matcher = Dmatcher::new();
+        matcher.insert("*.cdn.example.com", 1)?;
+        assert_eq!(matcher.matches("a.cdn.example.com")?, Some(1));
+        assert_eq!(matcher.matches("cdn.example.com")?, None);
+        assert_eq!(matcher.matches("x.y.cdn.example.com")?, None);
+        Ok(())
+    }
+
+    #[test]
+    fn exact_takes_priority_over_wildcard() -> ProtoResult<()> {
+        let mut matcher = Dmatcher::new();
+        matcher.insert("*.example.com", 1)?;
+        matcher.insert("api.example.com", 2)?;
+        assert_eq!(matcher.matches("api.example.com")?, Some(2));
+        assert_eq!(matcher.matches("other.example.com")?, Some(1));
+        Ok(())
+    }
+
+    #[test]
+    fn most_specific_ancestor_wins() -> ProtoResult<()> {
+        let mut matcher = Dmatcher::new();
+        matcher.insert("com", 1)?;
+        matcher.insert("apple.com", 2)?;
+        assert_eq!(matcher.matches("foo.com")?, Some(1));
+        assert_eq!(matcher.matches("store.apple.com")?, Some(2));
+        Ok(())
+    }
+
+    #[test]
+    fn exception_overrides_broader_rule() -> ProtoResult<()> {
+        let mut matcher = Dmatcher::new();
+        matcher.insert("*.example.com", 1)?;
+        matcher.insert_exception("safe.example.com")?;
+        assert_eq!(matcher.matches("ads.example.com")?, Some(1));
+        assert_eq!(matcher.matches("safe.example.com")?, None);
+        Ok(())
+    }
+
+    #[test]
+    fn pre_and_post_regex_rules() -> ProtoResult<()> {
+        let mut matcher = Dmatcher::new();
+        matcher.insert("example.com", 1).unwrap();
+        matcher.insert_pre_regex(r"^ads?[0-9]*\.", 2).unwrap();
+        matcher.insert_post_regex(r"\.ru$", 3).unwrap();
+        // pre rules run before the trie, so they can override an otherwise-matching rule.
+        assert_eq!(matcher.matches("ad1.example.com")?, Some(2));
+        assert_eq!(matcher.matches("store.example.com")?, Some(1));
+        // post rules are only consulted once the trie itself has no match.
+        assert_eq!(matcher.matches("evil.ru")?, Some(3));
+        assert_eq!(matcher.matches("other.cn")?, None);
+        Ok(())
+    }
+
+    #[test]
+    fn optimize_drops_redundant_leaves() -> ProtoResult<()> {
+        let mut matcher = Dmatcher::new();
+        matcher.insert("example.com", 1)?;
+        matcher.insert("www.example.com", 1)?;
+        matcher.optimize();
+        assert!(matcher
+            .root()
+            .next_lvs
+            .get(&Label::from_utf8("com")?)
+            .unwrap()
+            .next_lvs
+            .get(&Label::from_utf8("example")?)
+            .unwrap()
+            .next_lvs
+            .is_empty());
+        // Still resolves the same way after dropping the now-redundant child.
+        assert_eq!(matcher.matches("www.example.com")?, Some(1));
+        Ok(())
+    }
+
+    #[test]
+    fn freeze_preserves_matches() -> ProtoResult<()> {
+        let mut matcher = Dmatcher::new();
+        matcher.insert("apple.com", 1)?;
+        matcher.insert("*.example.com", 2)?;
+        matcher.insert("api.example.com", 3)?;
+        matcher.insert_exception("safe.example.com")?;
+        matcher.insert_post_regex(r"\.ru$", 4)?;
+        let frozen = matcher.freeze();
+        assert_eq!(frozen.matches("store.apple.com")?, Some(1));
+        assert_eq!(frozen.matches("other.example.com")?, Some(2));
+        assert_eq!(frozen.matches("api.example.com")?, Some(3));
+        assert_eq!(frozen.matches("safe.example.com")?, None);
+        assert_eq!(frozen.matches("evil.ru")?, Some(4));
+        assert_eq!(frozen.matches("baidu")?, None);
+        Ok(())
+    }
+
     #[test]
     fn insertion() -> ProtoResult<()> {
         let mut matcher = Dmatcher::new();
         matcher.insert("apple.com", 1)?;
         matcher.insert("apple.cn", 2)?;
-        println!("{:?}", matcher.get_root());
+        println!("{:?}", matcher.root());
         assert_eq!(
-            matcher.get_root(),
+            matcher.root(),
             &LevelNode {
-                dst: None,
+                dst: NodeValue::None,
+                wildcard: None,
                 next_lvs: [
                     (
                         Label::from_utf8("cn")?,
                         LevelNode {
-                            dst: None,
+                            dst: NodeValue::None,
+                            wildcard: None,
                             next_lvs: [(
                                 Label::from_utf8("apple")?,
                                 LevelNode {
-                                    dst: Some(2),
+                                    dst: NodeValue::Allow(2),
+                                    wildcard: None,
                                     next_lvs: []
                                         .iter()
                                         .cloned()
@@ -155,11 +535,13 @@ mod tests {
                     (
                         Label::from_utf8("com")?,
                         LevelNode {
-                            dst: None,
+                            dst: NodeValue::None,
+                            wildcard: None,
                             next_lvs: [(
                                 Label::from_utf8("apple")?,
                                 LevelNode {
-                                    dst: Some(1),
+                                    dst: NodeValue::Allow(1),
+                                    wildcard: None,
                                     next_lvs: []
                                         .iter()
                                         .cloned()
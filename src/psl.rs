@@ -0,0 +1,145 @@
+//! Load a [Public Suffix List](https://publicsuffix.org/) and extract the registrable domain
+//! (eTLD+1) for a name, reusing the crate's label trie rather than a dedicated PSL crate.
+
+use crate::{Dmatcher, LevelNode, NodeValue};
+use trust_dns_proto::error::ProtoResult;
+use trust_dns_proto::rr::domain::{IntoName, Label, Name};
+
+/// A matcher loaded with a Public Suffix List.
+#[derive(Debug, Clone, Default)]
+pub struct PublicSuffixList {
+    rules: Dmatcher<()>,
+}
+
+impl PublicSuffixList {
+    /// Create an empty list.
+    pub fn new() -> Self {
+        Self {
+            rules: Dmatcher::new(),
+        }
+    }
+
+    /// Parse a Public Suffix List in the `publicsuffix.org` `.dat` format: one rule per line,
+    /// leading `*.` wildcard rules, leading `!` exception rules, blank lines and `//` comments
+    /// ignored.
+    pub fn insert_lines(&mut self, list: &str) -> ProtoResult<()> {
+        for line in list.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with("//") {
+                continue;
+            }
+            match line.strip_prefix('!') {
+                Some(rule) => self.rules.insert_exception(rule)?,
+                None => self.rules.insert(line, ())?,
+            }
+        }
+        Ok(())
+    }
+
+    /// Return the registrable domain (eTLD+1) for `name`, or `None` if `name` is itself a
+    /// public suffix (or shorter).
+    pub fn registrable_domain<U: IntoName>(&self, name: U) -> ProtoResult<Option<Name>> {
+        let name = U::into_name(name)?;
+        let (value, depth) = prevailing_rule(self.rules.root(), &name)?;
+        let suffix_len = match value {
+            // An exception rule carves one label back off its own length, e.g. `!city.kawasaki.jp`
+            // makes `kawasaki.jp` (not `city.kawasaki.jp`) the public suffix.
+            NodeValue::Deny => depth.saturating_sub(1),
+            NodeValue::Allow(()) => depth,
+            // Nothing in the list covers this name; the implicit rule is `*`, i.e. the TLD
+            // itself is the public suffix.
+            NodeValue::None => 1,
+        };
+        if usize::from(name.num_labels()) > suffix_len {
+            Ok(Some(name.trim_to(suffix_len + 1)))
+        } else {
+            Ok(None)
+        }
+    }
+}
+
+/// Walk the suffix trie to find the longest (most specific) rule matching `name`, returning
+/// its value and the number of labels it covers. Unlike `Dmatcher::matches`, a wildcard here
+/// behaves like any other label match: resolution keeps descending past it for as long as the
+/// trie has structure, per the standard PSL resolution algorithm (a suffix rule, wildcard or
+/// not, still allows arbitrary subdomains beneath it).
+fn prevailing_rule<T: Copy>(
+    root: &LevelNode<T>,
+    name: &Name,
+) -> ProtoResult<(NodeValue<T>, usize)> {
+    let mut ptr = root;
+    let mut best = ptr.dst;
+    let mut best_depth = 0;
+    let mut depth = 0;
+    for lv in name.iter().rev() {
+        if !ptr.dst.is_none() {
+            best = ptr.dst;
+            best_depth = depth;
+        }
+        ptr = match ptr.next_lvs.get(&Label::from_raw_bytes(lv)?) {
+            Some(child) => child,
+            None => match &ptr.wildcard {
+                Some(w) => w,
+                None => break,
+            },
+        };
+        depth += 1;
+    }
+    if !ptr.dst.is_none() {
+        best = ptr.dst;
+        best_depth = depth;
+    }
+    Ok((best, best_depth))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::PublicSuffixList;
+    use trust_dns_proto::error::ProtoResult;
+
+    #[test]
+    fn plain_and_wildcard_rules() -> ProtoResult<()> {
+        let mut psl = PublicSuffixList::new();
+        psl.insert_lines("com\n*.ck\n")?;
+        assert_eq!(
+            psl.registrable_domain("store.apple.com")?
+                .map(|n| n.to_string()),
+            Some("apple.com".to_owned())
+        );
+        assert_eq!(psl.registrable_domain("com")?, None);
+        assert_eq!(
+            psl.registrable_domain("www.foo.ck")?.map(|n| n.to_string()),
+            Some("www.foo.ck".to_owned())
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn exception_rule_shortens_suffix() -> ProtoResult<()> {
+        let mut psl = PublicSuffixList::new();
+        psl.insert_lines("jp\n*.kawasaki.jp\n!city.kawasaki.jp\n")?;
+        assert_eq!(
+            psl.registrable_domain("www.city.kawasaki.jp")?
+                .map(|n| n.to_string()),
+            Some("city.kawasaki.jp".to_owned())
+        );
+        assert_eq!(
+            psl.registrable_domain("www.another.kawasaki.jp")?
+                .map(|n| n.to_string()),
+            Some("www.another.kawasaki.jp".to_owned())
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn unlisted_tld_falls_back_to_implicit_star() -> ProtoResult<()> {
+        let psl = PublicSuffixList::new();
+        assert_eq!(
+            psl.registrable_domain("example.unlisted")?
+                .map(|n| n.to_string()),
+            Some("example.unlisted".to_owned())
+        );
+        assert_eq!(psl.registrable_domain("unlisted")?, None);
+        Ok(())
+    }
+}